@@ -1,22 +1,38 @@
-use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::VartimeMultiscalarMul,
+};
 use digest::{
     consts::{U32, U64},
     generic_array::GenericArray,
     Digest, FixedOutput, Reset, Update,
 };
+use subtle::{Choice, ConstantTimeEq};
 
 #[derive(Clone, Default)]
 pub struct RistrettoHash<H> {
     hash: H,
     updating: bool,
     acc: RistrettoPoint,
+    key: Vec<u8>,
 }
 
 impl<H: Digest<OutputSize = U64> + Default> RistrettoHash<H> {
+    pub fn new_keyed(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            hash: H::default(),
+            updating: false,
+            acc: RistrettoPoint::default(),
+            key: key.as_ref().to_vec(),
+        }
+    }
+
     pub fn add(&mut self, data: impl AsRef<[u8]>, multiplicity: u64) {
         if self.updating {
             panic!("add called before end_update");
         }
+        self.hash.update(&self.key);
         self.hash.update(data);
         self.end_update(multiplicity);
     }
@@ -28,6 +44,76 @@ impl<H: Digest<OutputSize = U64> + Default> RistrettoHash<H> {
         let h_point = RistrettoPoint::from_hash(old);
         self.acc += Scalar::from(multiplicity) * h_point;
     }
+
+    pub fn add_batch(&mut self, items: impl IntoIterator<Item = (impl AsRef<[u8]>, u64)>) {
+        if self.updating {
+            panic!("add_batch called before end_update");
+        }
+
+        let mut scalars = Vec::new();
+        let mut points = Vec::new();
+        for (data, multiplicity) in items {
+            let mut hash = H::default();
+            hash.update(&self.key);
+            hash.update(data);
+            points.push(RistrettoPoint::from_hash(hash));
+            scalars.push(Scalar::from(multiplicity));
+        }
+
+        self.acc += RistrettoPoint::vartime_multiscalar_mul(&scalars, &points);
+    }
+
+    pub fn remove(&mut self, data: impl AsRef<[u8]>, multiplicity: u64) {
+        if self.updating {
+            panic!("remove called before end_update");
+        }
+        self.hash.update(&self.key);
+        self.hash.update(data);
+        self.end_remove(multiplicity);
+    }
+
+    pub fn end_remove(&mut self, multiplicity: u64) {
+        self.updating = false;
+
+        let old = std::mem::replace(&mut self.hash, H::default());
+        let h_point = RistrettoPoint::from_hash(old);
+        self.acc -= Scalar::from(multiplicity) * h_point;
+    }
+
+    pub fn state(&self) -> [u8; 32] {
+        if self.updating {
+            panic!("end_update not called before finalizing");
+        }
+        self.acc.compress().to_bytes()
+    }
+
+    pub fn from_state(bytes: &[u8; 32]) -> Option<Self> {
+        Self::from_state_keyed(bytes, b"")
+    }
+
+    pub fn from_state_keyed(bytes: &[u8; 32], key: impl AsRef<[u8]>) -> Option<Self> {
+        let acc = CompressedRistretto(*bytes).decompress()?;
+        Some(Self {
+            hash: H::default(),
+            updating: false,
+            acc,
+            key: key.as_ref().to_vec(),
+        })
+    }
+
+    pub fn union(&mut self, other: &Self) {
+        if self.updating || other.updating {
+            panic!("union called before end_update");
+        }
+        self.acc += other.acc;
+    }
+
+    pub fn verify(&self, expected: &[u8]) -> Choice {
+        if self.updating {
+            panic!("end_update not called before finalizing");
+        }
+        self.acc.compress().as_bytes()[..].ct_eq(expected)
+    }
 }
 
 impl<H: Reset> FixedOutput for RistrettoHash<H> {
@@ -59,6 +145,9 @@ impl<H: Reset> Reset for RistrettoHash<H> {
 
 impl<H: Update> Update for RistrettoHash<H> {
     fn update(&mut self, data: impl AsRef<[u8]>) {
+        if !self.updating {
+            self.hash.update(&self.key);
+        }
         self.updating = true;
         self.hash.update(data);
     }
@@ -123,6 +212,225 @@ mod test {
         assert_eq!(output1, output2)
     }
 
+    #[test]
+    fn test_add_batch_matches_sequential_add() {
+        let mut hash1 = RistrettoHash::<Sha512>::default();
+        hash1.add(b"test data A", 2);
+        hash1.add(b"test data B", 1);
+
+        let mut hash2 = RistrettoHash::<Sha512>::default();
+        hash2.add_batch([(b"test data A".as_slice(), 2), (b"test data B".as_slice(), 1)]);
+
+        assert_eq!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_remove_reverts_add() {
+        let data = b"test data";
+
+        let mut hash1 = RistrettoHash::<Sha512>::default();
+        let hash2 = hash1.clone();
+
+        hash1.add(data, 5);
+        hash1.remove(data, 5);
+
+        let output1 = hash1.finalize();
+        let output2 = hash2.finalize();
+        assert_eq!(output1, output2)
+    }
+
+    #[test]
+    fn test_remove_matches_negative_add() {
+        let data_a = b"test data A";
+        let data_b = b"test data B";
+
+        let mut hash1 = RistrettoHash::<Sha512>::default();
+        let mut hash2 = hash1.clone();
+
+        hash1.add(data_a, 3);
+        hash1.add(data_b, 2);
+        hash1.remove(data_b, 1);
+
+        hash2.add(data_a, 3);
+        hash2.add(data_b, 1);
+
+        let output1 = hash1.finalize();
+        let output2 = hash2.finalize();
+        assert_eq!(output1, output2)
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.add(b"test data", 2);
+
+        let state = hash.state();
+        let restored = RistrettoHash::<Sha512>::from_state(&state).unwrap();
+
+        assert_eq!(hash.finalize(), restored.finalize())
+    }
+
+    #[test]
+    fn test_from_state_rejects_invalid_encoding() {
+        // All-0xFF is not a valid compressed Ristretto point.
+        let bytes = [0xFFu8; 32];
+        assert!(RistrettoHash::<Sha512>::from_state(&bytes).is_none())
+    }
+
+    #[test]
+    fn test_union_matches_combined_input() {
+        let data_a = b"test data A";
+        let data_b = b"test data B";
+
+        let mut shard1 = RistrettoHash::<Sha512>::default();
+        shard1.add(data_a, 2);
+
+        let mut shard2 = RistrettoHash::<Sha512>::default();
+        shard2.add(data_b, 1);
+
+        let mut combined = shard1.clone();
+        combined.union(&shard2);
+
+        let mut expected = RistrettoHash::<Sha512>::default();
+        expected.add(data_a, 2);
+        expected.add(data_b, 1);
+
+        assert_eq!(combined.finalize(), expected.finalize())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_state_before_end_update_panics() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.update("some data");
+        hash.state();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_union_before_end_update_panics() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.update("some data");
+        hash.union(&RistrettoHash::<Sha512>::default());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_union_with_updating_other_panics() {
+        let mut other = RistrettoHash::<Sha512>::default();
+        other.update("some data");
+        RistrettoHash::<Sha512>::default().union(&other);
+    }
+
+    #[test]
+    fn test_verify_matches_finalize() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.add(b"test data", 1);
+
+        let expected = hash.clone().finalize();
+        assert!(bool::from(hash.verify(&expected)))
+    }
+
+    #[test]
+    fn test_verify_rejects_mismatch() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.add(b"test data", 1);
+
+        let wrong = [0u8; 32];
+        assert!(!bool::from(hash.verify(&wrong)))
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_verify_before_end_update_panics() {
+        let mut hash = RistrettoHash::<Sha512>::default();
+        hash.update("some data");
+        hash.verify(&[0u8; 32]);
+    }
+
+    #[test]
+    fn test_keyed_hash_differs_by_key() {
+        let data = b"test data";
+
+        let mut hash1 = RistrettoHash::<Sha512>::new_keyed(b"key one");
+        let mut hash2 = RistrettoHash::<Sha512>::new_keyed(b"key two");
+
+        hash1.add(data, 1);
+        hash2.add(data, 1);
+
+        assert_ne!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_keyed_hash_commutative() {
+        let data_a = b"test data A";
+        let data_b = b"test data B";
+
+        let mut hash1 = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        let mut hash2 = hash1.clone();
+
+        hash1.add(data_a, 1);
+        hash1.add(data_b, 1);
+
+        hash2.add(data_b, 1);
+        hash2.add(data_a, 1);
+
+        assert_eq!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_keyed_hash_partial_updates() {
+        let mut hash1 = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        let mut hash2 = hash1.clone();
+
+        hash1.add("the full data", 3);
+        hash2.update("the");
+        hash2.update(" full");
+        hash2.update(" data");
+        hash2.end_update(3);
+
+        assert_eq!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_keyed_add_batch_matches_sequential_add() {
+        let mut hash1 = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        hash1.add(b"test data A", 2);
+        hash1.add(b"test data B", 1);
+
+        let mut hash2 = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        hash2.add_batch([(b"test data A".as_slice(), 2), (b"test data B".as_slice(), 1)]);
+
+        assert_eq!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_keyed_remove_reverts_add() {
+        let data = b"test data";
+
+        let mut hash1 = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        let hash2 = hash1.clone();
+
+        hash1.add(data, 5);
+        hash1.remove(data, 5);
+
+        assert_eq!(hash1.finalize(), hash2.finalize())
+    }
+
+    #[test]
+    fn test_from_state_keyed_roundtrip() {
+        let mut hash = RistrettoHash::<Sha512>::new_keyed(b"shared key");
+        hash.add(b"test data", 2);
+
+        let state = hash.state();
+        let mut restored = RistrettoHash::<Sha512>::from_state_keyed(&state, b"shared key").unwrap();
+
+        hash.add(b"more data", 1);
+        restored.add(b"more data", 1);
+
+        assert_eq!(hash.finalize(), restored.finalize())
+    }
+
     #[test]
     #[should_panic]
     fn test_add_before_end_update_panics() {